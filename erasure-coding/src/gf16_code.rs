@@ -0,0 +1,621 @@
+// Copyright 2018 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A GF(2^16) systematic Reed-Solomon code, replacing the old
+//! `reed_solomon::galois_16` backend.
+//!
+//! `encode` interpolates the unique polynomial of degree < `data_shards`
+//! through the data symbols (via the vanishing polynomial of the node
+//! set and its formal derivative - the standard closed-form alternative
+//! to solving a Vandermonde system) and evaluates it at the remaining
+//! points; `reconstruct` does the same using whichever `data_shards`
+//! points survived. Both directions cost O(n * k) field operations per
+//! symbol, the same complexity class as the Vandermonde-based codec
+//! this replaces.
+//!
+//! This module does NOT implement the Lin-Han-Chung "novel polynomial
+//! basis" additive FFT (FOCS 2014) that chunk0-1 was originally scoped
+//! around, and so does not deliver that request's O(n log n) scaling
+//! goal - an attempt at the recursive subspace-decimation transform the
+//! real scheme needs didn't produce correct codewords under test, and
+//! was dropped rather than shipped half-working. Scaling availability
+//! to validator sets in the thousands is accordingly out of scope for
+//! this module as it stands; treat it as descoped from chunk0-1 and
+//! tracked separately, not as a renamed equivalent of the original ask.
+//! What's shipped here is a correctness-first, drop-in replacement for
+//! the old backend at the same asymptotic cost - no benchmark against
+//! `reed_solomon::galois_16` is included, so there's no evidence either
+//! way on constant-factor performance; treat this as a correctness and
+//! maintainability change, not a proven perf win, until one exists.
+
+use super::{wrapped_shard::WrappedShard, Error};
+
+/// A single GF(2^16) field element.
+pub(crate) type Elem = u16;
+
+/// The order of the field: all `u16` values are valid elements.
+pub(crate) const FIELD_SIZE: usize = 1 << 16;
+
+// x^16 + x^5 + x^3 + x^2 + 1, an irreducible polynomial over GF(2) used
+// to build the field's multiplication table.
+const FIELD_POLY: u32 = 0x1_002D;
+
+// the multiplicative group of GF(2^16) has order 65535 = 3 * 5 * 17 * 257.
+const GROUP_ORDER: u32 = (FIELD_SIZE - 1) as u32;
+const GROUP_ORDER_FACTORS: [u32; 4] = [3, 5, 17, 257];
+
+fn poly_mul(mut a: u32, mut b: u32) -> u32 {
+	let mut result = 0u32;
+	while b != 0 {
+		if b & 1 != 0 {
+			result ^= a;
+		}
+		b >>= 1;
+		a <<= 1;
+		if a & (1 << 16) != 0 {
+			a ^= FIELD_POLY;
+		}
+	}
+	result
+}
+
+fn poly_pow(mut base: u32, mut exp: u32) -> u32 {
+	let mut result = 1u32;
+	while exp != 0 {
+		if exp & 1 != 0 {
+			result = poly_mul(result, base);
+		}
+		base = poly_mul(base, base);
+		exp >>= 1;
+	}
+	result
+}
+
+fn is_generator(g: u32) -> bool {
+	GROUP_ORDER_FACTORS.iter().all(|&f| poly_pow(g, GROUP_ORDER / f) != 1)
+}
+
+/// Lookup tables for GF(2^16) arithmetic, built from the field's
+/// irreducible polynomial.
+pub(crate) struct GfTables {
+	log: Vec<u16>,
+	exp: Vec<u16>,
+}
+
+impl GfTables {
+	pub(crate) fn new() -> Self {
+		let mut generator = 2u32;
+		while !is_generator(generator) {
+			generator += 1;
+		}
+
+		let mut exp = vec![0u16; FIELD_SIZE - 1];
+		let mut log = vec![0u16; FIELD_SIZE];
+		let mut x = 1u32;
+		for i in 0..FIELD_SIZE - 1 {
+			exp[i] = x as u16;
+			log[x as usize] = i as u16;
+			x = poly_mul(x, generator);
+		}
+
+		GfTables { log, exp }
+	}
+
+	pub(crate) fn mul(&self, a: Elem, b: Elem) -> Elem {
+		if a == 0 || b == 0 {
+			return 0;
+		}
+		let sum = self.log[a as usize] as u32 + self.log[b as usize] as u32;
+		self.exp[(sum % GROUP_ORDER) as usize]
+	}
+
+	pub(crate) fn inv(&self, a: Elem) -> Elem {
+		debug_assert!(a != 0, "inverse of zero is undefined");
+		let neg_log = GROUP_ORDER - self.log[a as usize] as u32;
+		self.exp[(neg_log % GROUP_ORDER) as usize]
+	}
+}
+
+lazy_static! {
+	// building `GfTables` means searching for a generator and filling two
+	// 2^16-entry tables - do it once for the process rather than on every
+	// `encode`/`reconstruct` call (and, with segmented encoding, on every
+	// FEC block).
+	static ref GF: GfTables = GfTables::new();
+}
+
+// A polynomial over GF(2^16), stored as coefficients from the constant
+// term upward: `coeffs[i]` is the coefficient of `x^i`.
+#[derive(Clone, Debug)]
+struct Poly(Vec<Elem>);
+
+impl Poly {
+	fn zero(len: usize) -> Self {
+		Poly(vec![0; len])
+	}
+
+	// the monic polynomial `prod_i (x - roots[i])`. Addition and
+	// subtraction coincide in characteristic 2.
+	fn from_roots(gf: &GfTables, roots: &[Elem]) -> Self {
+		let mut coeffs = vec![0 as Elem; roots.len() + 1];
+		coeffs[0] = 1;
+
+		for (degree, &root) in roots.iter().enumerate() {
+			for i in (0..=degree + 1).rev() {
+				let from_lower = if i == 0 { 0 } else { coeffs[i - 1] };
+				let scaled = if i <= degree { gf.mul(coeffs[i], root) } else { 0 };
+				coeffs[i] = from_lower ^ scaled;
+			}
+		}
+
+		Poly(coeffs)
+	}
+
+	fn eval(&self, gf: &GfTables, x: Elem) -> Elem {
+		let mut acc: Elem = 0;
+		for &c in self.0.iter().rev() {
+			acc = gf.mul(acc, x) ^ c;
+		}
+		acc
+	}
+
+	// the formal derivative. In characteristic 2 every even-degree term
+	// vanishes, so only odd-degree coefficients survive, shifted down
+	// by one degree.
+	fn formal_derivative(&self) -> Poly {
+		if self.0.len() <= 1 {
+			return Poly::zero(1);
+		}
+		let mut out = vec![0 as Elem; self.0.len() - 1];
+		for i in (1..self.0.len()).step_by(2) {
+			out[i - 1] = self.0[i];
+		}
+		Poly(out)
+	}
+
+	// divide by `(x - root)`, assuming `root` is an actual root of
+	// `self` (the remainder is not checked).
+	fn divide_by_linear(&self, gf: &GfTables, root: Elem) -> Poly {
+		let n = self.0.len();
+		let mut quotient = vec![0 as Elem; n - 1];
+		let mut carry: Elem = 0;
+		for i in (0..n - 1).rev() {
+			carry = gf.mul(carry, root) ^ self.0[i + 1];
+			quotient[i] = carry;
+		}
+		Poly(quotient)
+	}
+
+	fn add_scaled_assign(&mut self, gf: &GfTables, other: &Poly, scale: Elem) {
+		if scale == 0 {
+			return;
+		}
+		for (a, &b) in self.0.iter_mut().zip(other.0.iter()) {
+			*a ^= gf.mul(b, scale);
+		}
+	}
+}
+
+// The Lagrange basis for a fixed set of evaluation points (nodes),
+// precomputed once and reused for every symbol column that shares the
+// same present/erased pattern: the vanishing polynomial of the node
+// set together with its formal derivative, which gives a closed form
+// for interpolation without solving a Vandermonde system from scratch
+// for every column.
+//
+// `basis_polys[i]` (the vanishing polynomial divided by `(x - nodes[i])`)
+// and `weights[i]` (`1 / vanishing'(nodes[i])`) depend only on `nodes`,
+// not on the symbol values being interpolated, so they're built once
+// here - an O(k^2) pass over the `k` nodes - rather than redone inside
+// `interpolate` on every symbol column, which would otherwise repeat
+// that O(k^2) pass `shard_len / 2` times per `encode`/`reconstruct`
+// call. `interpolate` itself is still O(k^2) per column (`k` basis
+// polynomials of degree `k - 1` summed with scaling), since producing a
+// codeword symbol unavoidably touches every data point without a fast
+// (FFT-based) multipoint evaluation - see the module doc for why that
+// isn't implemented here.
+struct LagrangeBasis {
+	nodes: Vec<Elem>,
+	basis_polys: Vec<Poly>,
+	// weights[i] = 1 / vanishing'(nodes[i])
+	weights: Vec<Elem>,
+}
+
+impl LagrangeBasis {
+	fn new(gf: &GfTables, nodes: Vec<Elem>) -> Self {
+		let vanishing = Poly::from_roots(gf, &nodes);
+		let derivative = vanishing.formal_derivative();
+		let basis_polys = nodes.iter().map(|&x| vanishing.divide_by_linear(gf, x)).collect();
+		let weights = nodes.iter().map(|&x| gf.inv(derivative.eval(gf, x))).collect();
+
+		LagrangeBasis { nodes, basis_polys, weights }
+	}
+
+	// the unique polynomial of degree < nodes.len() with `poly(nodes[i]) == ys[i]`.
+	fn interpolate(&self, gf: &GfTables, ys: &[Elem]) -> Poly {
+		let mut acc = Poly::zero(self.nodes.len());
+		for (i, &y_i) in ys.iter().enumerate() {
+			if y_i == 0 {
+				continue;
+			}
+			let scale = gf.mul(y_i, self.weights[i]);
+			acc.add_scaled_assign(gf, &self.basis_polys[i], scale);
+		}
+		acc
+	}
+}
+
+fn read_symbol(shard: &WrappedShard, symbol_index: usize) -> Elem {
+	let bytes = shard.as_ref();
+	let offset = symbol_index * 2;
+	u16::from_be_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn write_symbol(shard: &mut WrappedShard, symbol_index: usize, value: Elem) {
+	let bytes = shard.as_mut();
+	let offset = symbol_index * 2;
+	let encoded = value.to_be_bytes();
+	bytes[offset] = encoded[0];
+	bytes[offset + 1] = encoded[1];
+}
+
+/// A GF(2^16) Reed-Solomon codec for a fixed data/parity split.
+///
+/// Shards are systematic: shard `i` for `i < data_shards` always holds
+/// data symbol `i` verbatim, matching the indices callers already rely
+/// on from the Vandermonde-based codec this replaces.
+pub(crate) struct Codec {
+	data_shards: usize,
+	parity_shards: usize,
+}
+
+impl Codec {
+	pub(crate) fn new(data_shards: usize, parity_shards: usize) -> Self {
+		Codec { data_shards, parity_shards }
+	}
+
+	/// Fill in the parity shards (`data_shards..`) from the data shards
+	/// (`0..data_shards`) already present in `shards`.
+	pub(crate) fn encode(&self, shards: &mut [WrappedShard]) -> Result<(), Error> {
+		let n = self.data_shards + self.parity_shards;
+		if shards.len() != n {
+			return Err(Error::WrongValidatorCount);
+		}
+
+		let shard_len = shards[0].as_ref().len();
+		if shard_len == 0 || shard_len % 2 != 0 {
+			return Err(Error::UnevenLength);
+		}
+		if shards.iter().any(|s| s.as_ref().len() != shard_len) {
+			return Err(Error::NonUniformChunks);
+		}
+
+		let gf = &*GF;
+		let nodes: Vec<Elem> = (0..self.data_shards).map(|i| i as u16).collect();
+		let basis = LagrangeBasis::new(gf, nodes);
+		// `n` can be exactly `FIELD_SIZE` (65536), which overflows `u16` -
+		// cast each point after ranging in `usize`, not the bound itself,
+		// so a full-size validator set doesn't wrap `n as u16` to 0 and
+		// silently collapse every parity shard to zero.
+		let targets: Vec<Elem> = (self.data_shards..n).map(|i| i as u16).collect();
+
+		for symbol_index in 0..shard_len / 2 {
+			let ys: Vec<Elem> = shards[..self.data_shards]
+				.iter()
+				.map(|s| read_symbol(s, symbol_index))
+				.collect();
+
+			let poly = basis.interpolate(gf, &ys);
+			for (offset, &x) in targets.iter().enumerate() {
+				let value = poly.eval(gf, x);
+				write_symbol(&mut shards[self.data_shards + offset], symbol_index, value);
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Recover any missing data shards (`0..data_shards`) from whichever
+	/// shards are `Some` in `shards`. Missing parity shards are left as
+	/// `None`; nothing downstream reads them.
+	pub(crate) fn reconstruct(&self, shards: &mut [Option<WrappedShard>]) -> Result<(), Error> {
+		let n = self.data_shards + self.parity_shards;
+		if shards.len() != n {
+			return Err(Error::WrongValidatorCount);
+		}
+
+		let present: Vec<usize> = shards.iter()
+			.enumerate()
+			.filter_map(|(i, s)| if s.is_some() { Some(i) } else { None })
+			.collect();
+
+		if present.len() < self.data_shards {
+			return Err(Error::NotEnoughChunks);
+		}
+
+		let missing: Vec<usize> = (0..self.data_shards).filter(|i| shards[*i].is_none()).collect();
+		if missing.is_empty() {
+			return Ok(());
+		}
+
+		let shard_len = shards[present[0]].as_ref().expect("index is present; qed").as_ref().len();
+
+		let gf = &*GF;
+		let nodes: Vec<Elem> = present.iter().take(self.data_shards).map(|&i| i as u16).collect();
+		let basis = LagrangeBasis::new(gf, nodes.clone());
+
+		for &i in &missing {
+			shards[i] = Some(WrappedShard::new(vec![0; shard_len]));
+		}
+
+		for symbol_index in 0..shard_len / 2 {
+			let ys: Vec<Elem> = nodes.iter()
+				.map(|&x| read_symbol(shards[x as usize].as_ref().expect("node is present; qed"), symbol_index))
+				.collect();
+
+			let poly = basis.interpolate(gf, &ys);
+			for &i in &missing {
+				let value = poly.eval(gf, i as u16);
+				write_symbol(shards[i].as_mut().expect("just allocated; qed"), symbol_index, value);
+			}
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn field_is_consistent() {
+		let gf = GfTables::new();
+		for a in [1u16, 2, 3, 255, 65535].iter().cloned() {
+			assert_eq!(gf.mul(a, gf.inv(a)), 1, "a * a^-1 == 1 for a = {}", a);
+			assert_eq!(gf.mul(a, 0), 0);
+			assert_eq!(gf.mul(a, 1), a);
+		}
+	}
+
+	#[test]
+	fn vanishing_poly_has_roots() {
+		let gf = GfTables::new();
+		let roots = [1u16, 2, 7, 42];
+		let poly = Poly::from_roots(&gf, &roots);
+		for &r in &roots {
+			assert_eq!(poly.eval(&gf, r), 0);
+		}
+		assert_ne!(poly.eval(&gf, 99), 0);
+	}
+
+	#[test]
+	fn encode_then_reconstruct_round_trips() {
+		let codec = Codec::new(4, 6);
+		let mut shards: Vec<WrappedShard> = (0u16..10)
+			.map(|i| WrappedShard::new(vec![(i >> 8) as u8, i as u8, 0, (i * 2) as u8]))
+			.collect();
+
+		codec.encode(&mut shards).unwrap();
+
+		let mut opt_shards: Vec<Option<WrappedShard>> = shards.into_iter().map(Some).collect();
+		// keep only 4 shards (2..=5), enough to reconstruct the other 6.
+		for i in [0, 1, 6, 7, 8, 9] {
+			opt_shards[i] = None;
+		}
+
+		codec.reconstruct(&mut opt_shards).unwrap();
+
+		for i in 0..4u16 {
+			let shard = opt_shards[i as usize].as_ref().unwrap();
+			assert_eq!(read_symbol(shard, 0), i);
+		}
+	}
+
+	// a small, deterministic xorshift generator - good enough to exercise
+	// many erasure patterns without pulling in a `rand` dependency this
+	// repo doesn't otherwise have.
+	struct XorShift64(u64);
+
+	impl XorShift64 {
+		fn new(seed: u64) -> Self {
+			XorShift64(seed | 1)
+		}
+
+		fn next_u64(&mut self) -> u64 {
+			let mut x = self.0;
+			x ^= x << 13;
+			x ^= x >> 7;
+			x ^= x << 17;
+			self.0 = x;
+			x
+		}
+
+		// a value in `0..bound`.
+		fn below(&mut self, bound: usize) -> usize {
+			(self.next_u64() % bound as u64) as usize
+		}
+	}
+
+	// pick exactly `data_shards` indices out of `0..n`, in ascending order,
+	// simulating an arbitrary but minimal set of surviving shards.
+	fn random_surviving_indices(rng: &mut XorShift64, n: usize, data_shards: usize) -> Vec<usize> {
+		let mut indices: Vec<usize> = (0..n).collect();
+		// partial Fisher-Yates: shuffle just enough to pick `data_shards`
+		// indices uniformly without bias, then sort for readability.
+		for i in 0..data_shards {
+			let j = i + rng.below(n - i);
+			indices.swap(i, j);
+		}
+		let mut chosen = indices[..data_shards].to_vec();
+		chosen.sort_unstable();
+		chosen
+	}
+
+	#[test]
+	fn large_n_round_trip() {
+		let data_shards = 85;
+		let parity_shards = 170;
+		let n = data_shards + parity_shards;
+		let shard_len = 64;
+
+		let mut rng = XorShift64::new(0xC0FFEE);
+		let codec = Codec::new(data_shards, parity_shards);
+
+		let original: Vec<WrappedShard> = (0..data_shards)
+			.map(|_| {
+				let bytes: Vec<u8> = (0..shard_len).map(|_| rng.below(256) as u8).collect();
+				WrappedShard::new(bytes)
+			})
+			.collect();
+
+		let mut shards = original.clone();
+		shards.extend((0..parity_shards).map(|_| WrappedShard::new(vec![0; shard_len])));
+		codec.encode(&mut shards).unwrap();
+
+		let surviving = random_surviving_indices(&mut rng, n, data_shards);
+		let mut opt_shards: Vec<Option<WrappedShard>> = vec![None; n];
+		for &i in &surviving {
+			opt_shards[i] = Some(shards[i].clone());
+		}
+
+		codec.reconstruct(&mut opt_shards).unwrap();
+
+		for i in 0..data_shards {
+			assert_eq!(opt_shards[i].as_ref().unwrap().as_ref(), original[i].as_ref());
+		}
+	}
+
+	#[test]
+	fn random_erasure_patterns_round_trip() {
+		let mut rng = XorShift64::new(0xA5A5_5A5A);
+
+		// a spread of shard counts and data/parity splits, each tried
+		// against several independently-random erasure patterns.
+		for &(data_shards, parity_shards) in &[(1, 3), (2, 2), (5, 1), (7, 9), (16, 16)] {
+			let n = data_shards + parity_shards;
+			let shard_len = 2 * (1 + rng.below(8));
+
+			let codec = Codec::new(data_shards, parity_shards);
+			let original: Vec<WrappedShard> = (0..data_shards)
+				.map(|_| WrappedShard::new((0..shard_len).map(|_| rng.below(256) as u8).collect()))
+				.collect();
+
+			let mut shards = original.clone();
+			shards.extend((0..parity_shards).map(|_| WrappedShard::new(vec![0; shard_len])));
+			codec.encode(&mut shards).unwrap();
+
+			for _ in 0..20 {
+				let surviving = random_surviving_indices(&mut rng, n, data_shards);
+				let mut opt_shards: Vec<Option<WrappedShard>> = vec![None; n];
+				for &i in &surviving {
+					opt_shards[i] = Some(shards[i].clone());
+				}
+
+				codec.reconstruct(&mut opt_shards).unwrap();
+
+				for i in 0..data_shards {
+					assert_eq!(
+						opt_shards[i].as_ref().unwrap().as_ref(),
+						original[i].as_ref(),
+						"data_shards={} parity_shards={} surviving={:?}",
+						data_shards, parity_shards, surviving,
+					);
+				}
+			}
+		}
+	}
+
+	// solve the Vandermonde system directly (Gaussian elimination, O(k^3))
+	// for the unique degree-<k polynomial through `(nodes[i], ys[i])`,
+	// independently of `Poly::from_roots`/`divide_by_linear` - a cross-check
+	// that the closed-form path those two functions implement isn't just
+	// internally self-consistent but actually solves the same system a
+	// textbook method would.
+	fn vandermonde_interpolate(gf: &GfTables, nodes: &[Elem], ys: &[Elem]) -> Poly {
+		let k = nodes.len();
+		let mut matrix: Vec<Vec<Elem>> = nodes.iter().zip(ys.iter())
+			.map(|(&x, &y)| {
+				let mut row = vec![0 as Elem; k + 1];
+				let mut power = 1u16;
+				for j in 0..k {
+					row[j] = power;
+					power = gf.mul(power, x);
+				}
+				row[k] = y;
+				row
+			})
+			.collect();
+
+		for col in 0..k {
+			let pivot = (col..k).find(|&r| matrix[r][col] != 0)
+				.expect("nodes are distinct, so the Vandermonde matrix is non-singular");
+			matrix.swap(col, pivot);
+
+			let inv = gf.inv(matrix[col][col]);
+			for j in col..=k {
+				matrix[col][j] = gf.mul(matrix[col][j], inv);
+			}
+
+			for r in 0..k {
+				if r != col && matrix[r][col] != 0 {
+					let factor = matrix[r][col];
+					for j in col..=k {
+						matrix[r][j] ^= gf.mul(factor, matrix[col][j]);
+					}
+				}
+			}
+		}
+
+		Poly((0..k).map(|i| matrix[i][k]).collect())
+	}
+
+	#[test]
+	fn encode_matches_independent_vandermonde_solve() {
+		let gf = GfTables::new();
+		let mut rng = XorShift64::new(0xDEAD_BEEF);
+
+		let data_shards = 6;
+		let parity_shards = 4;
+		let n = data_shards + parity_shards;
+
+		let nodes: Vec<Elem> = (0..data_shards as u16).collect();
+		let ys: Vec<Elem> = (0..data_shards).map(|_| rng.below(FIELD_SIZE) as u16).collect();
+
+		let basis = LagrangeBasis::new(&gf, nodes.clone());
+		let via_lagrange = basis.interpolate(&gf, &ys);
+		let via_vandermonde = vandermonde_interpolate(&gf, &nodes, &ys);
+
+		for x in 0..n as u16 {
+			assert_eq!(
+				via_lagrange.eval(&gf, x), via_vandermonde.eval(&gf, x),
+				"Lagrange and Vandermonde solves disagree at x = {}", x,
+			);
+		}
+
+		let mut shards: Vec<WrappedShard> = ys.iter()
+			.map(|&y| WrappedShard::new(y.to_be_bytes().to_vec()))
+			.collect();
+		shards.extend((0..parity_shards).map(|_| WrappedShard::new(vec![0; 2])));
+
+		Codec::new(data_shards, parity_shards).encode(&mut shards).unwrap();
+
+		for (offset, x) in (data_shards as u16..n as u16).enumerate() {
+			let expected = via_vandermonde.eval(&gf, x);
+			assert_eq!(read_symbol(&shards[data_shards + offset], 0), expected);
+		}
+	}
+}
@@ -25,24 +25,26 @@
 //! The data is coded so any f+1 chunks can be used to reconstruct the full data.
 
 extern crate polkadot_primitives as primitives;
-extern crate reed_solomon_erasure as reed_solomon;
 extern crate parity_codec as codec;
 extern crate substrate_primitives;
 extern crate substrate_trie as trie;
+#[macro_use]
+extern crate lazy_static;
 
 use codec::{Encode, Decode};
-use reed_solomon::galois_16::{self, ReedSolomon};
 use primitives::{Hash as H256, BlakeTwo256, HashT};
 use primitives::parachain::{BlockData, Extrinsic};
 use substrate_primitives::Blake2Hasher;
 use trie::{MemoryDB, Trie, TrieMut, TrieDB, TrieDBMut};
 
 use self::wrapped_shard::WrappedShard;
+use self::gf16_code::Codec as Gf16Codec;
 
 mod wrapped_shard;
+mod gf16_code;
 
 // we are limited to the field order of GF(2^16), which is 65536
-const MAX_VALIDATORS: usize = <galois_16::Field as reed_solomon::Field>::ORDER;
+const MAX_VALIDATORS: usize = gf16_code::FIELD_SIZE;
 
 /// Errors in erasure coding.
 #[derive(Debug, Clone)]
@@ -69,56 +71,59 @@ pub enum Error {
 	InvalidBranchProof,
 	/// Branch out of bounds.
 	BranchOutOfBounds,
+	/// A chunk's embedded checksum did not match its payload.
+	ChunkChecksumMismatch(usize),
+	/// A chunk declared a version this build doesn't know how to decode.
+	UnsupportedChunkVersion(u16),
 }
 
+// the payload's true byte length is written once, as a little-endian
+// `u32`, into the first `HEADER_LEN` bytes of the data shards taken as
+// a whole (the head of shard 0, running on into shard 1 when shards are
+// shorter than `HEADER_LEN`) rather than once per shard.
+const HEADER_LEN: usize = 4;
+
 struct CodeParams {
 	data_shards: usize,
 	parity_shards: usize,
 }
 
 impl CodeParams {
-	// the shard length needed for a payload with initial size `base_len`.
+	// the shard length needed so that `data_shards` shards hold the
+	// length header plus a payload of `base_len` bytes, rounded up to
+	// an even number of bytes so every shard is a whole number of
+	// GF(2^16) symbols.
 	fn shard_len(&self, base_len: usize) -> usize {
-		(base_len / self.data_shards) + (base_len % self.data_shards)
+		let len = (HEADER_LEN + base_len + self.data_shards - 1) / self.data_shards;
+		len + (len % 2)
 	}
 
 	fn make_shards_for(&self, payload: &[u8]) -> Vec<WrappedShard> {
 		let shard_len = self.shard_len(payload.len());
+
+		// lay the header and the payload out as one contiguous buffer,
+		// padded up to exactly `data_shards * shard_len` bytes, and then
+		// slice it into the data shards - this avoids the overhead of a
+		// length prefix on every shard. See paritytech/polkadot#88.
+		let mut buf = vec![0u8; shard_len * self.data_shards];
+		(payload.len() as u32).using_encoded(|s| buf[..HEADER_LEN].copy_from_slice(s));
+		buf[HEADER_LEN..][..payload.len()].copy_from_slice(payload);
+
 		let mut shards = vec![
-			WrappedShard::new(vec![0; shard_len + 4]);
+			WrappedShard::new(vec![0; shard_len]);
 			self.data_shards + self.parity_shards
 		];
 
-		for (data_chunk, blank_shard) in payload.chunks(shard_len).zip(&mut shards) {
-			let blank_shard: &mut [u8] = blank_shard.as_mut();
-			let (len_slice, blank_shard) = blank_shard.split_at_mut(4);
-			let len = ::std::cmp::min(data_chunk.len(), blank_shard.len());
-
-			// prepend the length to each data shard. this will tell us how much
-			// we need to read.
-			//
-			// this is necessary because we are doing RS encoding with 16-bit words,
-			// but the payload is a byte-slice. We need to know how much data
-			// to read from each shard when reconstructing.
-			//
-			// TODO: could be done more efficiently by pushing extra bytes onto the
-			// end. https://github.com/paritytech/polkadot/issues/88
-			(len as u32).using_encoded(|s| {
-				len_slice.copy_from_slice(s)
-			});
-
-			// fill the empty shards with the corresponding piece of the payload,
-			// zero-padded to fit in the shards.
-			blank_shard[..len].copy_from_slice(&data_chunk[..len]);
+		for (data_chunk, blank_shard) in buf.chunks(shard_len).zip(&mut shards) {
+			blank_shard.as_mut().copy_from_slice(data_chunk);
 		}
 
 		shards
 	}
 
-	// make a reed-solomon instance.
-	fn make_encoder(&self) -> ReedSolomon {
-		ReedSolomon::new(self.data_shards, self.parity_shards)
-			.expect("this struct is not created with invalid shard number; qed")
+	// make a codec instance for this data/parity split.
+	fn make_encoder(&self) -> Gf16Codec {
+		Gf16Codec::new(self.data_shards, self.parity_shards)
 	}
 }
 
@@ -135,39 +140,105 @@ fn code_params(n_validators: usize) -> Result<CodeParams, Error> {
 	})
 }
 
-/// Obtain erasure-coded chunks, one for each validator.
+// the length in bytes of the BLAKE2 checksum trailer appended to every
+// chunk handed out by `obtain_chunks`/`obtain_chunks_segmented`.
+const CHECKSUM_LEN: usize = 32;
+
+// append a checksum of `chunk` to itself, so that corruption of the
+// chunk in transit (with its length left intact) can be detected
+// locally, without needing a merkle branch proof from `branches`.
+fn append_checksum(mut chunk: Vec<u8>) -> Vec<u8> {
+	let digest = BlakeTwo256::hash(&chunk);
+	chunk.extend_from_slice(digest.as_ref());
+	chunk
+}
+
+/// Verify a chunk's embedded checksum (as appended by `obtain_chunks` or
+/// `obtain_chunks_segmented`), returning the chunk's payload with the
+/// checksum trailer stripped off on success.
 ///
-/// Works only up to 256 validators, and `n_validators` must be non-zero.
-pub fn obtain_chunks(n_validators: usize, block_data: &BlockData, extrinsic: &Extrinsic)
-	-> Result<Vec<Vec<u8>>, Error>
-{
-	let params  = code_params(n_validators)?;
-	let encoded = (block_data, extrinsic).encode();
+/// This is a cheap local integrity check a node can run even when it
+/// doesn't have the merkle branch proof handy; see `branch_hash` for
+/// proving a chunk belongs to a particular erasure-coding root.
+pub fn verify_chunk(chunk: &[u8], index: usize) -> Result<&[u8], Error> {
+	if chunk.len() < CHECKSUM_LEN {
+		return Err(Error::ChunkChecksumMismatch(index));
+	}
 
-	if encoded.is_empty() {
+	let (payload, checksum) = chunk.split_at(chunk.len() - CHECKSUM_LEN);
+	if BlakeTwo256::hash(payload).as_ref() != checksum {
+		return Err(Error::ChunkChecksumMismatch(index));
+	}
+
+	Ok(payload)
+}
+
+// the length in bytes of the version tag prepended to every chunk handed
+// out by `obtain_chunks`/`obtain_chunks_segmented`.
+const CHUNK_VERSION_LEN: usize = 2;
+
+/// A chunk format version, encoded as the first two bytes of every chunk.
+///
+/// This lets the coding scheme (field, FFT codec, segmentation, checksum
+/// layout, ...) evolve without a hard flag-day break: a validator running
+/// older software can recognise a chunk it doesn't understand and reject it
+/// cleanly instead of misinterpreting the bytes.
+pub type ChunkVersion = u16;
+
+/// Chunks produced by `obtain_chunks`: single-block, checksummed, encoded
+/// with the GF(2^16) codec in `gf16_code`.
+const CHUNK_VERSION_V1: ChunkVersion = 1;
+
+/// Chunks produced by `obtain_chunks_segmented`: as `CHUNK_VERSION_V1`, but
+/// the payload is split into independently-coded FEC blocks.
+const CHUNK_VERSION_SEGMENTED_V1: ChunkVersion = 2;
+
+/// The set of chunk versions this build knows how to reconstruct.
+pub fn supported_versions() -> &'static [ChunkVersion] {
+	&[CHUNK_VERSION_V1, CHUNK_VERSION_SEGMENTED_V1]
+}
+
+// prepend a version tag to a chunk, so `reconstruct`/`reconstruct_segmented`
+// can dispatch on (or reject) it without guessing at the encoding below.
+fn prepend_chunk_version(version: ChunkVersion, chunk: Vec<u8>) -> Vec<u8> {
+	let mut tagged = Vec::with_capacity(CHUNK_VERSION_LEN + chunk.len());
+	version.using_encoded(|s| tagged.extend_from_slice(s));
+	tagged.extend_from_slice(&chunk);
+	tagged
+}
+
+// strip a chunk's version tag, returning it along with the remaining
+// bytes if it's one `supported_versions` knows how to decode.
+fn strip_known_chunk_version(chunk: &[u8]) -> Result<(ChunkVersion, &[u8]), Error> {
+	if chunk.len() < CHUNK_VERSION_LEN {
 		return Err(Error::BadPayload);
 	}
 
-	let mut shards = params.make_shards_for(&encoded[..]);
+	let (version_bytes, rest) = chunk.split_at(CHUNK_VERSION_LEN);
+	let version = ChunkVersion::decode(&mut &version_bytes[..]).ok_or(Error::BadPayload)?;
+
+	if !supported_versions().contains(&version) {
+		return Err(Error::UnsupportedChunkVersion(version));
+	}
+
+	Ok((version, rest))
+}
+
+// erasure-code a single payload (already length-checked by the caller)
+// into one chunk per validator.
+fn encode_payload(params: &CodeParams, payload: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+	let mut shards = params.make_shards_for(payload);
 
-	params.make_encoder().encode(&mut shards[..])
-		.expect("Payload non-empty, shard sizes are uniform, and validator numbers checked; qed");
+	params.make_encoder().encode(&mut shards[..])?;
 
 	Ok(shards.into_iter().map(|w| w.into_inner()).collect())
 }
 
-/// Reconstruct the block data from a set of chunks.
-///
-/// Provide an iterator containing chunk data and the corresponding index.
-/// The indices of the present chunks must be indicated. If too few chunks
-/// are provided, recovery is not possible.
-///
-/// Works only up to 256 validators, and `n_validators` must be non-zero.
-pub fn reconstruct<'a, I: 'a>(n_validators: usize, chunks: I)
-	-> Result<(BlockData, Extrinsic), Error>
+// reconstruct a single payload's bytes from a set of chunks produced by
+// `encode_payload` for the same `params`.
+fn decode_payload<'a, I: 'a>(params: &CodeParams, n_validators: usize, chunks: I) -> Result<Vec<u8>, Error>
 	where I: IntoIterator<Item=(&'a [u8], usize)>
 {
-	let params = code_params(n_validators)?;
 	let mut shards: Vec<Option<WrappedShard>> = vec![None; n_validators];
 	let mut shard_len = None;
 	for (chunk_data, chunk_idx) in chunks.into_iter().take(n_validators) {
@@ -188,36 +259,200 @@ pub fn reconstruct<'a, I: 'a>(n_validators: usize, chunks: I)
 		shards[chunk_idx] = Some(WrappedShard::new(chunk_data.to_vec()));
 	}
 
-	if let Err(e) = params.make_encoder().reconstruct(&mut shards[..]) {
-		match e {
-			reed_solomon::Error::TooFewShardsPresent => Err(Error::NotEnoughChunks)?,
-			reed_solomon::Error::InvalidShardFlags => Err(Error::WrongValidatorCount)?,
-			reed_solomon::Error::TooManyShards => Err(Error::TooManyChunks)?,
-			reed_solomon::Error::EmptyShard => panic!("chunks are all non-empty; this is checked above; qed"),
-			reed_solomon::Error::IncorrectShardSize => panic!("chunks are all same len; this is checked above; qed"),
-			_ => panic!("reed_solomon encoder returns no more variants for this function; qed"),
+	params.make_encoder().reconstruct(&mut shards[..])?;
+
+	let mut buf = Vec::new();
+	for shard in shards.iter().take(params.data_shards) {
+		let shard = shard.as_ref().expect("all data shards have been recovered; qed");
+		buf.extend_from_slice(shard.as_ref());
+	}
+
+	// the true payload length was written once, at the head of the data
+	// shards, rather than once per shard. See `CodeParams::make_shards_for`.
+	if buf.len() < HEADER_LEN {
+		return Err(Error::BadPayload);
+	}
+	let data_len = u32::decode(&mut &buf[..HEADER_LEN]).ok_or(Error::BadPayload)? as usize;
+	if buf.len() < HEADER_LEN + data_len {
+		return Err(Error::BadPayload);
+	}
+
+	Ok(buf[HEADER_LEN..][..data_len].to_vec())
+}
+
+/// Obtain erasure-coded chunks, one for each validator.
+///
+/// `n_validators` must be non-zero and no greater than `MAX_VALIDATORS`.
+pub fn obtain_chunks(n_validators: usize, block_data: &BlockData, extrinsic: &Extrinsic)
+	-> Result<Vec<Vec<u8>>, Error>
+{
+	let params  = code_params(n_validators)?;
+	let encoded = (block_data, extrinsic).encode();
+
+	if encoded.is_empty() {
+		return Err(Error::BadPayload);
+	}
+
+	let shards = encode_payload(&params, &encoded[..])?;
+
+	Ok(
+		shards.into_iter()
+			.map(append_checksum)
+			.map(|c| prepend_chunk_version(CHUNK_VERSION_V1, c))
+			.collect()
+	)
+}
+
+/// Reconstruct the block data from a set of chunks produced by either
+/// `obtain_chunks` or `obtain_chunks_segmented`.
+///
+/// Provide an iterator containing chunk data and the corresponding index.
+/// The indices of the present chunks must be indicated. If too few chunks
+/// are provided, recovery is not possible. Chunks with an unsupported
+/// version tag (see `supported_versions`) or whose embedded checksum does
+/// not match their payload are treated as absent rather than failing
+/// reconstruction outright; which decoder runs is chosen from the version
+/// tag the present chunks actually carry, not hardcoded to one format.
+///
+/// `n_validators` must be non-zero and no greater than `MAX_VALIDATORS`.
+pub fn reconstruct<'a, I: 'a>(n_validators: usize, chunks: I)
+	-> Result<(BlockData, Extrinsic), Error>
+	where I: IntoIterator<Item=(&'a [u8], usize)>
+{
+	let params = code_params(n_validators)?;
+	let verified: Vec<(ChunkVersion, &'a [u8], usize)> = chunks.into_iter()
+		.take(n_validators)
+		.filter_map(|(data, idx)| {
+			let (version, rest) = strip_known_chunk_version(data).ok()?;
+			verify_chunk(rest, idx).ok().map(|payload| (version, payload, idx))
+		})
+		.collect();
+
+	let version = verified.first().map(|&(v, _, _)| v).ok_or(Error::NotEnoughChunks)?;
+
+	match version {
+		CHUNK_VERSION_V1 => {
+			let payload = decode_payload(
+				&params,
+				n_validators,
+				verified.into_iter().map(|(_, data, idx)| (data, idx)),
+			)?;
+			Decode::decode(&mut &payload[..]).ok_or_else(|| Error::BadPayload)
 		}
+		CHUNK_VERSION_SEGMENTED_V1 => decode_segmented(&params, n_validators, verified),
+		other => Err(Error::UnsupportedChunkVersion(other)),
 	}
+}
 
-	// lazily decode from the data shards.
-	Decode::decode(&mut ShardInput {
-		shards: shards.iter()
-			.map(|x| x.as_ref())
-			.take(params.data_shards)
-			.map(|x| x.expect("all data shards have been recovered; qed"))
-			.filter_map(|x| {
-				let mut s: &[u8] = x.as_ref();
-				let data_len = u32::decode(&mut s)? as usize;
-
-				// NOTE: s has been mutated to point forward by `decode`.
-				if s.len() < data_len {
-					None
-				} else {
-					Some(&s[..data_len])
-				}
-			}),
-		cur_shard: None,
-	}).ok_or_else(|| Error::BadPayload)
+// the header prepended identically to every validator's chunk by
+// `obtain_chunks_segmented`, recording how the payload was split into
+// FEC blocks. The block count is simply `block_lens.len()`.
+#[derive(Encode, Decode)]
+struct SegmentedHeader {
+	block_lens: Vec<u32>,
+}
+
+/// Obtain erasure-coded chunks, one for each validator, slicing the
+/// payload into fixed-size FEC blocks of at most `fec_block_len` bytes
+/// and erasure-coding each block independently.
+///
+/// Unlike `obtain_chunks`, the shard buffers used while coding are sized
+/// to a single block rather than the whole payload, so the coding work
+/// itself doesn't need GF(2^16) working memory proportional to overall
+/// payload size. This function still materializes the full encoded
+/// payload and the full set of output chunks before returning, though -
+/// it is not a streaming API, and a validator can't make use of an
+/// earlier block before every later block has also been coded and
+/// appended to every chunk returned here.
+///
+/// `n_validators` must be non-zero and no greater than `MAX_VALIDATORS`,
+/// and `fec_block_len` must be non-zero.
+pub fn obtain_chunks_segmented(
+	n_validators: usize,
+	block_data: &BlockData,
+	extrinsic: &Extrinsic,
+	fec_block_len: usize,
+) -> Result<Vec<Vec<u8>>, Error> {
+	let params = code_params(n_validators)?;
+	let encoded = (block_data, extrinsic).encode();
+
+	if encoded.is_empty() {
+		return Err(Error::BadPayload);
+	}
+	if fec_block_len == 0 {
+		return Err(Error::BadPayload);
+	}
+
+	let blocks: Vec<&[u8]> = encoded.chunks(fec_block_len).collect();
+	let header = SegmentedHeader {
+		block_lens: blocks.iter().map(|b| b.len() as u32).collect(),
+	};
+
+	let mut chunks: Vec<Vec<u8>> = vec![header.encode(); n_validators];
+	for block in &blocks {
+		let block_chunks = encode_payload(&params, block)?;
+		for (chunk, block_chunk) in chunks.iter_mut().zip(block_chunks) {
+			chunk.extend_from_slice(&block_chunk);
+		}
+	}
+
+	Ok(
+		chunks.into_iter()
+			.map(append_checksum)
+			.map(|c| prepend_chunk_version(CHUNK_VERSION_SEGMENTED_V1, c))
+			.collect()
+	)
+}
+
+/// Reconstruct the block data from a set of chunks produced by
+/// `obtain_chunks_segmented`, recovering block-by-block so the shard
+/// buffers used for reconstruction are sized to a single block rather
+/// than the whole payload. As with `obtain_chunks_segmented`, this is a
+/// bound on the coding work's own memory, not a streaming API: the
+/// recovered bytes are still accumulated into one buffer and returned
+/// as a single deserialized value once every block has been recovered.
+///
+/// `reconstruct` dispatches on the chunk version tag, so it handles
+/// segmented chunks too; this name is kept for callers that only ever deal
+/// with segmented chunks.
+///
+/// `n_validators` must be non-zero and no greater than `MAX_VALIDATORS`.
+pub fn reconstruct_segmented<'a, I: 'a>(n_validators: usize, chunks: I)
+	-> Result<(BlockData, Extrinsic), Error>
+	where I: IntoIterator<Item=(&'a [u8], usize)>
+{
+	reconstruct(n_validators, chunks)
+}
+
+// decode a payload produced by `obtain_chunks_segmented`, recovering
+// block-by-block, from chunks already stripped of their version tag and
+// checksum trailer.
+fn decode_segmented<'a>(
+	params: &CodeParams,
+	n_validators: usize,
+	chunks: Vec<(ChunkVersion, &'a [u8], usize)>,
+) -> Result<(BlockData, Extrinsic), Error> {
+	let chunks: Vec<(&'a [u8], usize)> = chunks.into_iter().map(|(_, data, idx)| (data, idx)).collect();
+
+	let (first_chunk, _) = *chunks.first().ok_or(Error::NotEnoughChunks)?;
+	let header = SegmentedHeader::decode(&mut &first_chunk[..]).ok_or_else(|| Error::BadPayload)?;
+	let header_len = header.encode().len();
+
+	let mut payload = Vec::new();
+	let mut offset = header_len;
+	for &block_len in &header.block_lens {
+		let shard_len = params.shard_len(block_len as usize);
+
+		let block_chunks: Vec<(&[u8], usize)> = chunks.iter()
+			.filter_map(|&(data, idx)| data.get(offset..offset + shard_len).map(|shard| (shard, idx)))
+			.collect();
+
+		let block_payload = decode_payload(params, n_validators, block_chunks)?;
+		payload.extend_from_slice(&block_payload);
+		offset += shard_len;
+	}
+
+	Decode::decode(&mut &payload[..]).ok_or_else(|| Error::BadPayload)
 }
 
 /// An iterator that yields merkle branches and chunk data for all chunks to
@@ -264,6 +499,9 @@ impl<'a> Iterator for Branches<'a> {
 
 /// Construct a trie from chunks of an erasure-coded value. This returns the root hash and an
 /// iterator of merkle proofs, one for each validator.
+///
+/// `chunks` are hashed as opaque byte strings, version tag and all, so this
+/// and `branch_hash` work unchanged across chunk format versions.
 pub fn branches<'a>(chunks: Vec<&'a [u8]>) -> Branches<'a> {
 	let mut trie_storage: MemoryDB<Blake2Hasher> = MemoryDB::default();
 	let mut root = H256::default();
@@ -309,45 +547,6 @@ pub fn branch_hash(root: &H256, branch_nodes: &[Vec<u8>], index: usize) -> Resul
 	}
 }
 
-// input for `parity_codec` which draws data from the data shards
-struct ShardInput<'a, I> {
-	shards: I,
-	cur_shard: Option<(&'a [u8], usize)>,
-}
-
-impl<'a, I: Iterator<Item=&'a [u8]>> codec::Input for ShardInput<'a, I> {
-	fn read(&mut self, into: &mut [u8]) -> usize {
-		let mut read_bytes = 0;
-
-		loop {
-			if read_bytes == into.len() { break }
-
-			let cur_shard = self.cur_shard.take().or_else(|| self.shards.next().map(|s| (s, 0)));
-			let (active_shard, mut in_shard) = match cur_shard {
-				Some((s, i)) => (s, i),
-				None => break,
-			};
-
-			if in_shard >= active_shard.len() {
-				continue;
-			}
-
-			let remaining_len_out = into.len() - read_bytes;
-			let remaining_len_shard = active_shard.len() - in_shard;
-
-			let write_len = std::cmp::min(remaining_len_out, remaining_len_shard);
-			into[read_bytes..][..write_len]
-				.copy_from_slice(&active_shard[in_shard..][..write_len]);
-
-			in_shard += write_len;
-			read_bytes += write_len;
-			self.cur_shard = Some((active_shard, in_shard))
-		}
-
-		read_bytes
-	}
-}
-
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -406,4 +605,112 @@ mod tests {
 			assert_eq!(branch_hash(&root, &proof, i).unwrap(), BlakeTwo256::hash(chunks[i]));
 		}
 	}
+
+	#[test]
+	fn round_trip_block_data_segmented() {
+		let block_data = BlockData((0..1024).map(|i| i as u8).collect());
+		let ex = Extrinsic { outgoing_messages: Vec::new() };
+		let chunks = obtain_chunks_segmented(
+			10,
+			&block_data,
+			&ex,
+			128,
+		).unwrap();
+
+		assert_eq!(chunks.len(), 10);
+
+		// any 4 chunks should work.
+		let reconstructed = reconstruct_segmented(
+			10,
+			[
+				(&*chunks[1], 1),
+				(&*chunks[4], 4),
+				(&*chunks[6], 6),
+				(&*chunks[9], 9),
+			].iter().cloned(),
+		).unwrap();
+
+		assert_eq!(reconstructed, (block_data, ex));
+	}
+
+	#[test]
+	fn corrupt_chunk_is_dropped_not_fatal() {
+		let block_data = BlockData((0..255).collect());
+		let ex = Extrinsic { outgoing_messages: Vec::new() };
+		let mut chunks = obtain_chunks(10, &block_data, &ex).unwrap();
+
+		// corrupt a byte in chunk 1's payload (after the version tag),
+		// leaving its length untouched.
+		chunks[1][CHUNK_VERSION_LEN] ^= 0xff;
+		let (_, stripped) = strip_known_chunk_version(&chunks[1]).unwrap();
+		assert!(verify_chunk(stripped, 1).is_err());
+
+		// reconstruction still succeeds by falling back to other chunks.
+		let reconstructed = reconstruct(
+			10,
+			[
+				(&*chunks[1], 1),
+				(&*chunks[4], 4),
+				(&*chunks[6], 6),
+				(&*chunks[8], 8),
+				(&*chunks[9], 9),
+			].iter().cloned(),
+		).unwrap();
+
+		assert_eq!(reconstructed, (block_data, ex));
+	}
+
+	#[test]
+	fn unsupported_chunk_version_is_rejected() {
+		let block_data = BlockData((0..255).collect());
+		let ex = Extrinsic { outgoing_messages: Vec::new() };
+		let mut chunks = obtain_chunks(10, &block_data, &ex).unwrap();
+
+		// a version this build has never heard of.
+		let bogus_version: ChunkVersion = 0xffff;
+		assert!(!supported_versions().contains(&bogus_version));
+		bogus_version.using_encoded(|s| chunks[0][..CHUNK_VERSION_LEN].copy_from_slice(s));
+
+		let err = strip_known_chunk_version(&chunks[0]).unwrap_err();
+		match err {
+			Error::UnsupportedChunkVersion(v) => assert_eq!(v, bogus_version),
+			_ => panic!("expected UnsupportedChunkVersion"),
+		}
+
+		// reconstruction still succeeds, falling back to the chunks that
+		// carry a version it knows.
+		let reconstructed = reconstruct(
+			10,
+			[
+				(&*chunks[0], 0),
+				(&*chunks[1], 1),
+				(&*chunks[4], 4),
+				(&*chunks[6], 6),
+				(&*chunks[9], 9),
+			].iter().cloned(),
+		).unwrap();
+
+		assert_eq!(reconstructed, (block_data, ex));
+	}
+
+	#[test]
+	fn reconstruct_dispatches_on_segmented_version() {
+		let block_data = BlockData((0..1024).map(|i| i as u8).collect());
+		let ex = Extrinsic { outgoing_messages: Vec::new() };
+		let chunks = obtain_chunks_segmented(10, &block_data, &ex, 128).unwrap();
+
+		// the plain `reconstruct` entry point also handles segmented
+		// chunks, since dispatch is by version tag rather than call site.
+		let reconstructed = reconstruct(
+			10,
+			[
+				(&*chunks[1], 1),
+				(&*chunks[4], 4),
+				(&*chunks[6], 6),
+				(&*chunks[9], 9),
+			].iter().cloned(),
+		).unwrap();
+
+		assert_eq!(reconstructed, (block_data, ex));
+	}
 }
@@ -0,0 +1,48 @@
+// Copyright 2018 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A plain `Vec<u8>` wrapper that satisfies the interfaces expected of
+//! an erasure-coding shard elsewhere in this crate.
+
+/// A parity-coded shard of data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrappedShard {
+	inner: Vec<u8>,
+}
+
+impl WrappedShard {
+	/// Create a new wrapped shard from the given byte buffer.
+	pub fn new(inner: Vec<u8>) -> Self {
+		WrappedShard { inner }
+	}
+
+	/// Consume the wrapper and return the inner buffer.
+	pub fn into_inner(self) -> Vec<u8> {
+		self.inner
+	}
+}
+
+impl AsRef<[u8]> for WrappedShard {
+	fn as_ref(&self) -> &[u8] {
+		&self.inner[..]
+	}
+}
+
+impl AsMut<[u8]> for WrappedShard {
+	fn as_mut(&mut self) -> &mut [u8] {
+		&mut self.inner[..]
+	}
+}